@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Counter {
+    /* Count every b interval overlapping an a interval */
+    All,
+    /* Count an a interval as a single hit if any b interval overlaps it */
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Randomizer {
+    /* Shift all intervals downstream together, wrapping around the genome */
+    Circle,
+    /* Move each interval independently to a new position */
+    Shuffle,
+    /* Move each interval independently without letting any overlap */
+    Novl,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum InputFormat {
+    Bed,
+    Bam,
+    Cram,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum IndexKind {
+    /* rust_lapper's interval tree */
+    Lapper,
+    /* Augmented Interval List - faster queries against dense, nested intervals */
+    Ailist,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+pub struct ArgParser {
+    /// Bed file of intervals to test for overlap with `bed_b`
+    #[arg(short = 'a', long)]
+    pub bed_a: PathBuf,
+
+    /// Bed file of intervals to test for overlap with `bed_a`
+    #[arg(short = 'b', long)]
+    pub bed_b: PathBuf,
+
+    /// Genome file of chromosome lengths
+    #[arg(short, long)]
+    pub genome: PathBuf,
+
+    /// Bed file of regions to exclude from randomization
+    #[arg(short, long)]
+    pub mask: Option<PathBuf>,
+
+    /// Output json file path
+    #[arg(short, long, default_value = "output.json")]
+    pub output: PathBuf,
+
+    /// Master seed for the permutation RNGs. Omit for a random, non-reproducible seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Input format of bed_a/bed_b. Auto-detected from the file extension when omitted
+    #[arg(long, value_enum)]
+    pub format: Option<InputFormat>,
+
+    /// FASTA used to decode CRAM records (required for CRAM input)
+    #[arg(long)]
+    pub reference: Option<PathBuf>,
+
+    /// Minimum mapping quality for a BAM/CRAM record to be included
+    #[arg(long, default_value_t = 0)]
+    pub min_mapq: u8,
+
+    /// Include secondary alignments (excluded by default)
+    #[arg(long)]
+    pub include_secondary: bool,
+
+    /// Include supplementary alignments (excluded by default)
+    #[arg(long)]
+    pub include_supplementary: bool,
+
+    /// Overlap index backing bed_b's queries
+    #[arg(long, value_enum, default_value_t = IndexKind::Lapper)]
+    pub index: IndexKind,
+
+    /// Number of permutations to run
+    #[arg(short = 'n', long, default_value_t = 100)]
+    pub num_times: u32,
+
+    /// Number of threads to use
+    #[arg(short, long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Randomization strategy
+    #[arg(short, long, value_enum, default_value_t = Randomizer::Shuffle)]
+    pub random: Randomizer,
+
+    /// Overlap counting strategy
+    #[arg(short, long, value_enum, default_value_t = Counter::All)]
+    pub count: Counter,
+
+    /// Randomize within each interval's chromosome instead of genome-wide
+    #[arg(long)]
+    pub per_chrom: bool,
+
+    /// Skip merging overlapping intervals within each bed file
+    #[arg(long)]
+    pub no_merge: bool,
+
+    /// Skip swapping A for the shorter of A/B
+    #[arg(long)]
+    pub no_swap: bool,
+}
+
+pub fn validate_args(args: &ArgParser) -> bool {
+    let mut is_ok = true;
+
+    if !args.bed_a.exists() {
+        error!("bed_a {:?} does not exist", args.bed_a);
+        is_ok = false;
+    }
+    if !args.bed_b.exists() {
+        error!("bed_b {:?} does not exist", args.bed_b);
+        is_ok = false;
+    }
+    if !args.genome.exists() {
+        error!("genome {:?} does not exist", args.genome);
+        is_ok = false;
+    }
+    if let Some(m) = &args.mask {
+        if !m.exists() {
+            error!("mask {:?} does not exist", m);
+            is_ok = false;
+        }
+    }
+    if let Some(r) = &args.reference {
+        if !r.exists() {
+            error!("reference {:?} does not exist", r);
+            is_ok = false;
+        }
+    }
+    if args.threads == 0 {
+        error!("threads must be >= 1");
+        is_ok = false;
+    }
+    if args.num_times == 0 {
+        error!("num_times must be >= 1");
+        is_ok = false;
+    }
+
+    is_ok
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use rust_htslib::bam::{self, Read as BamRead};
+use rust_lapper::{Interval, Lapper};
+
+use crate::cli;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/* A single genomic interval lifted into the flattened, genome-wide
+*  coordinate space (see GenomeShift below). `val` is unused by the
+*  overlappers but is required by rust_lapper's Interval type.
+*/
+pub type Iv = Interval<u64, u64>;
+
+/* regioneR-style tools need to treat every chromosome as one contiguous
+*  span so a single Lapper can be built over the whole genome instead of
+*  one per chromosome. GenomeShift records, for each chromosome, the
+*  offset at which it begins in that flattened space (`chrom`, whose
+*  interval `val` is the chromosome's length minus its masked bases)
+*  along with the total
+*  flattened length (`span`). `gap_budget` is filled in later by
+*  `novl` randomization and otherwise left `None`.
+*/
+#[derive(Clone)]
+pub struct GenomeShift {
+    pub chrom: Lapper<u64, u64>,
+    pub offsets: HashMap<String, u64>,
+    pub span: u64,
+    pub gap_budget: Option<HashMap<u64, u64>>,
+}
+
+/* Open `path` for line-oriented reading, transparently decompressing it
+*  if it's gzip/bgzip (sniffed from its first two bytes) so callers never
+*  need to zcat bed/genome files ahead of time.
+*/
+fn open_lines<P: AsRef<Path>>(path: P) -> Box<dyn BufRead> {
+    let mut file = File::open(&path)
+        .unwrap_or_else(|e| panic!("unable to open {:?}: {}", path.as_ref(), e));
+
+    let mut magic = [0u8; 2];
+    let read = file.read(&mut magic).expect("unable to read file header");
+    let mut file = BufReader::new(file);
+    file.seek_relative(-(read as i64))
+        .expect("unable to rewind file");
+
+    if read == 2 && magic == GZIP_MAGIC {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    }
+}
+
+/* Read a BED3+ file of regions to exclude from randomization, e.g.
+*  assembly gaps or low-mappability regions. Coordinates are kept in
+*  per-chromosome space and looked up by chromosome name.
+*/
+pub fn read_mask(path: &std::path::PathBuf) -> HashMap<String, Lapper<u64, u64>> {
+    info!("reading mask {:?}", path);
+    let mut per_chrom: HashMap<String, Vec<Iv>> = HashMap::new();
+    for line in open_lines(path).lines() {
+        let line = line.expect("error reading mask line");
+        let mut fields = line.split('\t');
+        let chrom = fields.next().expect("malformed mask line").to_string();
+        let start: u64 = fields
+            .next()
+            .expect("malformed mask line")
+            .parse()
+            .expect("non-integer mask start");
+        let stop: u64 = fields
+            .next()
+            .expect("malformed mask line")
+            .parse()
+            .expect("non-integer mask stop");
+        per_chrom
+            .entry(chrom)
+            .or_default()
+            .push(Iv { start, stop, val: 0 });
+    }
+    per_chrom
+        .into_iter()
+        .map(|(chrom, ivs)| (chrom, Lapper::<u64, u64>::new(ivs)))
+        .collect()
+}
+
+/* Read a two-column (chrom, length) genome file and lay the chromosomes
+*  end-to-end in a single flattened coordinate space, subtracting out
+*  any masked bases so randomization never places an interval there.
+*/
+pub fn read_genome(
+    path: &std::path::PathBuf,
+    mask: &Option<HashMap<String, Lapper<u64, u64>>>,
+) -> GenomeShift {
+    info!("reading genome {:?}", path);
+    let mut chrom_ivs = Vec::<Iv>::new();
+    let mut offsets = HashMap::<String, u64>::new();
+    let mut cur_offset: u64 = 0;
+
+    for line in open_lines(path).lines() {
+        let line = line.expect("error reading genome line");
+        let mut fields = line.split('\t');
+        let chrom = fields.next().expect("malformed genome line").to_string();
+        let length: u64 = fields
+            .next()
+            .expect("malformed genome line")
+            .parse()
+            .expect("non-integer chrom length");
+
+        let masked: u64 = match mask {
+            Some(m) => m
+                .get(&chrom)
+                .map(|l| l.iter().map(|i| i.stop - i.start).sum())
+                .unwrap_or(0),
+            None => 0,
+        };
+        let usable = length - masked;
+
+        offsets.insert(chrom, cur_offset);
+        chrom_ivs.push(Iv {
+            start: cur_offset,
+            stop: cur_offset + usable,
+            val: usable,
+        });
+        cur_offset += usable;
+    }
+
+    GenomeShift {
+        span: cur_offset,
+        chrom: Lapper::<u64, u64>::new(chrom_ivs),
+        offsets,
+        gap_budget: None,
+    }
+}
+
+/* Read a BED3+ file and lift every interval into the genome's flattened
+*  coordinate space via `genome.offsets`. Intervals overlapping a
+*  masked region are dropped.
+*/
+pub fn read_bed(
+    path: &std::path::PathBuf,
+    genome: &GenomeShift,
+    mask: &Option<HashMap<String, Lapper<u64, u64>>>,
+) -> Lapper<u64, u64> {
+    info!("reading bed {:?}", path);
+    let mut ret = Vec::<Iv>::new();
+
+    for line in open_lines(path).lines() {
+        let line = line.expect("error reading bed line");
+        let mut fields = line.split('\t');
+        let chrom = fields.next().expect("malformed bed line").to_string();
+        let start: u64 = fields
+            .next()
+            .expect("malformed bed line")
+            .parse()
+            .expect("non-integer bed start");
+        let stop: u64 = fields
+            .next()
+            .expect("malformed bed line")
+            .parse()
+            .expect("non-integer bed stop");
+
+        if let Some(m) = mask {
+            if let Some(l) = m.get(&chrom) {
+                if l.find(start, stop).next().is_some() {
+                    continue;
+                }
+            }
+        }
+
+        let offset = *genome
+            .offsets
+            .get(&chrom)
+            .unwrap_or_else(|| panic!("{} not found in genome file", chrom));
+        ret.push(Iv {
+            start: offset + start,
+            stop: offset + stop,
+            val: 0,
+        });
+    }
+
+    Lapper::<u64, u64>::new(ret)
+}
+
+fn sniff_format(path: &Path) -> cli::InputFormat {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("bam") => cli::InputFormat::Bam,
+        Some("cram") => cli::InputFormat::Cram,
+        _ => cli::InputFormat::Bed,
+    }
+}
+
+/* Read mapped alignments out of a BAM/CRAM file and lift them into the
+*  genome's flattened coordinate space the same way `read_bed` does.
+*  Unmapped records are always dropped; secondary/supplementary records
+*  and a minimum mapping quality are filterable via the cli flags.
+*/
+#[allow(clippy::too_many_arguments)]
+fn read_align(
+    path: &std::path::PathBuf,
+    genome: &GenomeShift,
+    mask: &Option<HashMap<String, Lapper<u64, u64>>>,
+    reference: &Option<std::path::PathBuf>,
+    min_mapq: u8,
+    include_secondary: bool,
+    include_supplementary: bool,
+) -> Lapper<u64, u64> {
+    info!("reading alignments {:?}", path);
+    let mut reader = bam::Reader::from_path(path)
+        .unwrap_or_else(|e| panic!("unable to open {:?}: {}", path, e));
+    if let Some(r) = reference {
+        reader
+            .set_reference(r)
+            .unwrap_or_else(|e| panic!("unable to set reference {:?}: {}", r, e));
+    }
+    let header = reader.header().clone();
+
+    let mut ret = Vec::<Iv>::new();
+    for record in reader.records() {
+        let record = record.expect("error reading alignment record");
+
+        if record.is_unmapped() {
+            continue;
+        }
+        if record.is_secondary() && !include_secondary {
+            continue;
+        }
+        if record.is_supplementary() && !include_supplementary {
+            continue;
+        }
+        if record.mapq() < min_mapq {
+            continue;
+        }
+
+        let chrom = std::str::from_utf8(header.tid2name(record.tid() as u32))
+            .expect("non-utf8 reference name")
+            .to_string();
+        let start = record.pos() as u64;
+        let stop = record.cigar().end_pos() as u64;
+
+        if let Some(m) = mask {
+            if let Some(l) = m.get(&chrom) {
+                if l.find(start, stop).next().is_some() {
+                    continue;
+                }
+            }
+        }
+
+        let offset = *genome
+            .offsets
+            .get(&chrom)
+            .unwrap_or_else(|| panic!("{} not found in genome file", chrom));
+        ret.push(Iv {
+            start: offset + start,
+            stop: offset + stop,
+            val: 0,
+        });
+    }
+
+    Lapper::<u64, u64>::new(ret)
+}
+
+/* Entry point main uses for both `bed_a` and `bed_b`: dispatches to the
+*  BED or BAM/CRAM reader based on `--format`, falling back to sniffing
+*  the file extension when it's not given.
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn read_intervals(
+    path: &std::path::PathBuf,
+    genome: &GenomeShift,
+    mask: &Option<HashMap<String, Lapper<u64, u64>>>,
+    format: Option<cli::InputFormat>,
+    reference: &Option<std::path::PathBuf>,
+    min_mapq: u8,
+    include_secondary: bool,
+    include_supplementary: bool,
+) -> Lapper<u64, u64> {
+    match format.unwrap_or_else(|| sniff_format(path)) {
+        cli::InputFormat::Bed => read_bed(path, genome, mask),
+        cli::InputFormat::Bam | cli::InputFormat::Cram => read_align(
+            path,
+            genome,
+            mask,
+            reference,
+            min_mapq,
+            include_secondary,
+            include_supplementary,
+        ),
+    }
+}
@@ -5,8 +5,10 @@ extern crate log;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::thread;
+use std::sync::Mutex;
 
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use tinyrand::{Rand, RandRange, Seeded, StdRand};
 use tinyrand_std::clock_seed::ClockSeed;
 
@@ -14,9 +16,12 @@ use clap::Parser;
 use rust_lapper::Lapper;
 use serde_json::json;
 
+mod ailist;
 mod cli;
 mod io;
 
+use ailist::AIList;
+
 const NOVLMAGIC: u64 = 10000;
 /* When performing novl randomization, we break the uncovered spans of
 *  the genome into pieces and shuffle them along with the intervals.
@@ -37,12 +42,12 @@ fn shuffle_intervals(
     intv: &Lapper<u64, u64>,
     genome: &io::GenomeShift,
     per_chrom: bool,
+    rand: &mut StdRand,
 ) -> Lapper<u64, u64> {
     /*
         Randomly move each interval to new position
     */
     let mut ret = Vec::<io::Iv>::new();
-    let mut rand = StdRand::seed(ClockSeed::default().next_u64());
 
     for i in intv.iter() {
         let (lower, upper) = if per_chrom {
@@ -67,11 +72,11 @@ fn circle_intervals(
     intv: &Lapper<u64, u64>,
     genome: &io::GenomeShift,
     per_chrom: bool,
+    rand: &mut StdRand,
 ) -> Lapper<u64, u64> {
     /*
         Randomly shift all intervals downstream with wrap-around
     */
-    let mut rand = StdRand::seed(ClockSeed::default().next_u64());
     let mut ret = Vec::<io::Iv>::new();
 
     let genome_shift: u64 = rand.next_range(0..(genome.span));
@@ -121,11 +126,11 @@ fn novl_intervals(
     intv: &Lapper<u64, u64>,
     genome: &io::GenomeShift,
     per_chrom: bool,
+    rand: &mut StdRand,
 ) -> Lapper<u64, u64> {
     /*
         Randomly move each interval to new position without overlapping them
     */
-    let mut rand = StdRand::seed(ClockSeed::default().next_u64());
     let mut ret: Vec<io::Iv> = vec![];
 
     let spans = match per_chrom {
@@ -156,7 +161,12 @@ fn novl_intervals(
             intv.find(subi.start, subi.stop)
                 .map(|i| (true, i.stop - i.start)),
         );
-        fastrand::shuffle(&mut cur_intervals);
+        // Fisher-Yates, driven by the thread-local RNG so the whole
+        // permutation is reproducible from a single master seed.
+        for i in (1..cur_intervals.len()).rev() {
+            let j = rand.next_range(0..(i + 1));
+            cur_intervals.swap(i, j);
+        }
 
         let mut cur_pos = subi.start;
         for i in cur_intervals {
@@ -174,25 +184,43 @@ fn novl_intervals(
     Lapper::<u64, u64>::new(ret)
 }
 
+// bed_b's overlap index: either rust_lapper's interval tree or the
+// AIList built in `main`, selected via `--index`. Both share the
+// find/count semantics the overlappers below need.
+enum OverlapIndex {
+    Lapper(Lapper<u64, u64>),
+    Ailist(AIList),
+}
+
+impl OverlapIndex {
+    fn count(&self, start: u64, stop: u64) -> u64 {
+        match self {
+            OverlapIndex::Lapper(l) => l.find(start, stop).count() as u64,
+            OverlapIndex::Ailist(a) => a.count(start, stop),
+        }
+    }
+
+    fn any(&self, start: u64, stop: u64) -> bool {
+        match self {
+            OverlapIndex::Lapper(l) => l.find(start, stop).next().is_some(),
+            OverlapIndex::Ailist(a) => a.any(start, stop),
+        }
+    }
+}
+
 // **********
 // Overlapers
 // **********
-fn get_num_overlap_count(a_lap: &Lapper<u64, u64>, b_lap: &Lapper<u64, u64>) -> u64 {
+fn get_num_overlap_count(a_lap: &Lapper<u64, u64>, b_lap: &OverlapIndex) -> u64 {
     /* Return number of b intervals intersecting each of a's intervals */
-    a_lap
-        .iter()
-        .map(|i| b_lap.find(i.start, i.stop).count() as u64)
-        .sum()
+    a_lap.iter().map(|i| b_lap.count(i.start, i.stop)).sum()
 }
 
-fn get_any_overlap_count(a_lap: &Lapper<u64, u64>, b_lap: &Lapper<u64, u64>) -> u64 {
+fn get_any_overlap_count(a_lap: &Lapper<u64, u64>, b_lap: &OverlapIndex) -> u64 {
     /* Return number of a intervals intersecting b intervals */
     a_lap
         .iter()
-        .map(|i| match b_lap.find(i.start, i.stop).next() {
-            Some(_) => 1,
-            None => 0,
-        })
+        .map(|i| u64::from(b_lap.any(i.start, i.stop)))
         .sum()
 }
 
@@ -228,6 +256,23 @@ fn count_permutations(o_count: u64, obs: &Vec<u64>, alt: char) -> f64 {
     g_count
 }
 
+fn splitmix64(mut x: u64) -> u64 {
+    /*
+        Cheap, well-mixed derivation of one u64 from another. Used to turn
+        a single master --seed into an independent seed per permutation
+        without the permutations' RNG streams correlating.
+    */
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn make_perm_rng(seed: u64, perm_index: u64) -> StdRand {
+    StdRand::seed(splitmix64(seed ^ perm_index))
+}
+
 // Should probably go into an implementation of GenomeShift
 fn make_gap_budget(
     genome: &io::GenomeShift,
@@ -269,8 +314,26 @@ fn main() -> std::io::Result<()> {
 
     let mask = args.mask.map(|p| io::read_mask(&p));
     let mut genome = io::read_genome(&args.genome, &mask);
-    let mut a_lapper = io::read_bed(&args.bed_a, &genome, &mask);
-    let mut b_lapper = io::read_bed(&args.bed_b, &genome, &mask);
+    let mut a_lapper = io::read_intervals(
+        &args.bed_a,
+        &genome,
+        &mask,
+        args.format,
+        &args.reference,
+        args.min_mapq,
+        args.include_secondary,
+        args.include_supplementary,
+    );
+    let mut b_lapper = io::read_intervals(
+        &args.bed_b,
+        &genome,
+        &mask,
+        args.format,
+        &args.reference,
+        args.min_mapq,
+        args.include_secondary,
+        args.include_supplementary,
+    );
 
     // Setup
     if !args.no_merge {
@@ -286,6 +349,11 @@ fn main() -> std::io::Result<()> {
         }
         false => false,
     };
+    let b_cnt = b_lapper.len();
+    let b_index = match args.index {
+        cli::IndexKind::Lapper => OverlapIndex::Lapper(b_lapper),
+        cli::IndexKind::Ailist => OverlapIndex::Ailist(AIList::new(b_lapper.iter().cloned().collect())),
+    };
     let overlapper = match args.count {
         cli::Counter::Any => get_any_overlap_count,
         cli::Counter::All => get_num_overlap_count,
@@ -307,32 +375,29 @@ fn main() -> std::io::Result<()> {
         .unwrap();*/
 
     // Processing
-    let initial_overlap_count: u64 = overlapper(&a_lapper, &b_lapper);
+    let initial_overlap_count: u64 = overlapper(&a_lapper, &b_index);
     info!("{} intersections", initial_overlap_count);
 
-    let mut handles = Vec::new();
-    let chunk_size: u32 = ((args.num_times as f32) / (args.threads as f32)).ceil() as u32;
-
-    for i in 0..args.threads as u32 {
-        let m_a = a_lapper.clone();
-        let m_b = b_lapper.clone();
-        let m_genome = genome.clone();
-        // Send chunk to thread
-        let start_iter = i * chunk_size;
-        let stop_iter = std::cmp::min(start_iter + chunk_size, args.num_times);
-        handles.push(thread::spawn(move || {
-            (start_iter..stop_iter)
-                .map(|_| overlapper(&randomizer(&m_a, &m_genome, args.per_chrom), &m_b))
-                .collect()
-        }));
-    }
+    let master_seed = args.seed.unwrap_or_else(|| ClockSeed::default().next_u64());
+    info!("seed: {}", master_seed);
 
-    // Collect
-    let mut all_counts: Vec<u64> = vec![];
-    for handle in handles {
-        let result: Vec<u64> = handle.join().unwrap();
-        all_counts.extend(result);
-    }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build()
+        .expect("failed to build thread pool");
+
+    // Each permutation seeds its own StdRand from the master seed and its
+    // index, so its result does not depend on which worker thread runs it
+    // and a run replays exactly from its seed at any thread count.
+    let all_counts: Vec<u64> = pool.install(|| {
+        (0..args.num_times)
+            .into_par_iter()
+            .map(|perm| {
+                let mut rng = make_perm_rng(master_seed, perm as u64);
+                overlapper(&randomizer(&a_lapper, &genome, args.per_chrom, &mut rng), &b_index)
+            })
+            .collect()
+    });
     /*if let Ok(report) = guard.report().build() {
         println!("report: {:?}", &report);
     };*/
@@ -366,12 +431,13 @@ fn main() -> std::io::Result<()> {
                       "perm_sd": sd,
                       "alt": alt,
                       "n": args.num_times,
+                      "seed": master_seed,
                       "swapped": swapped,
                       "no_merge": args.no_merge,
                       "random": args.random as u8,
                       "counter": args.count as u8,
                       "A_cnt" : a_lapper.len(),
-                      "B_cnt" : b_lapper.len(),
+                      "B_cnt" : b_cnt,
                       "per_chrom": args.per_chrom,
                       "perms": all_counts});
     let json_str = serde_json::to_string(&data).unwrap();